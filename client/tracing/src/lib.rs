@@ -23,15 +23,30 @@
 //! See `sp-tracing` for examples on how to use tracing.
 //!
 //! Currently we provide `Log` (default), `Telemetry` variants for `Receiver`
+//! as well as `Json` (newline-delimited JSON on stdout) and `File` (a rotating log file).
+//!
+//! `ProfilingLayer` is a `tracing_subscriber::Layer` built on top of a shared
+//! `Registry`, so it can be stacked with a formatting layer, an `EnvFilter`, or
+//! other layers under one global dispatcher rather than having to be the sole
+//! `Subscriber` in the process, e.g.:
+//!
+//! ```ignore
+//! let layer = ProfilingLayer::new(TracingReceiver::Log, "pallet=trace");
+//! let subscriber = tracing_subscriber::Registry::default().with(layer).with(fmt::Layer::default());
+//! tracing::subscriber::set_global_default(subscriber)?;
+//! ```
 
 use rustc_hash::FxHashMap;
 use std::fmt;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Duration as ChronoDuration, Timelike, Utc};
 use parking_lot::Mutex;
 use serde::ser::{Serialize, Serializer, SerializeMap};
-use slog::{SerdeValue, Value};
+use slog::{SerdeValue, Value as SlogValue};
 use tracing_core::{
 	event::Event,
 	field::{Visit, Field},
@@ -40,9 +55,13 @@ use tracing_core::{
 	span::{Attributes, Id, Record},
 	subscriber::Subscriber,
 };
-use tracing_subscriber::CurrentSpan;
+use tracing_subscriber::{
+	layer::{Context, Layer},
+	registry::{LookupSpan, SpanRef},
+};
 
 use sc_telemetry::{telemetry, SUBSTRATE_INFO};
+use serde_json::json;
 use sp_tracing::proxy::{WASM_NAME_KEY, WASM_TARGET_KEY, WASM_TRACE_IDENTIFIER};
 
 const ZERO_DURATION: Duration = Duration::from_nanos(0);
@@ -57,6 +76,68 @@ pub enum TracingReceiver {
 	Log,
 	/// Output to telemetry
 	Telemetry,
+	/// Output newline-delimited JSON to stdout
+	Json,
+	/// Output to a rotating on-disk log file
+	File(FileConfig),
+}
+
+/// How often a `FileTraceHandler`'s log file is rotated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+	Hourly,
+	Daily,
+	Never,
+}
+
+impl Rotation {
+	/// The `chrono` format string used to render the date component of a rotated
+	/// file's name, or `None` if this rotation never rotates (and so has no date
+	/// component at all).
+	fn date_format(&self) -> Option<&'static str> {
+		match self {
+			Rotation::Hourly => Some("%Y-%m-%d-%H"),
+			Rotation::Daily => Some("%Y-%m-%d"),
+			Rotation::Never => None,
+		}
+	}
+
+	/// The length of time until the next rotation boundary, or `None` if this
+	/// rotation never rotates.
+	fn period(&self) -> Option<ChronoDuration> {
+		match self {
+			Rotation::Hourly => Some(ChronoDuration::hours(1)),
+			Rotation::Daily => Some(ChronoDuration::days(1)),
+			Rotation::Never => None,
+		}
+	}
+
+	/// The next calendar-aligned rotation boundary strictly after `now`, or `None` if
+	/// this rotation never rotates. Truncates `now` down to the start of the current
+	/// hour/day before adding one period, so the boundary lines up with the hour/day
+	/// the rotated file is named after, regardless of when the previous rotation ran.
+	fn next_boundary_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+		let period = self.period()?;
+		let truncated = match self {
+			Rotation::Hourly => now.date().and_hms(now.hour(), 0, 0),
+			Rotation::Daily => now.date().and_hms(0, 0, 0),
+			Rotation::Never => return None,
+		};
+		Some(truncated + period)
+	}
+}
+
+/// Configuration for a rolling `FileTraceHandler`.
+#[derive(Debug, Clone)]
+pub struct FileConfig {
+	/// Directory the log files are written into.
+	pub directory: PathBuf,
+	/// Filename prefix, e.g. "substrate".
+	pub prefix: String,
+	/// Optional filename suffix, e.g. "log".
+	pub suffix: Option<String>,
+	/// How often to rotate to a new file.
+	pub rotation: Rotation,
 }
 
 impl Default for TracingReceiver {
@@ -92,51 +173,271 @@ pub struct SpanDatum {
 	pub target: String,
 	pub level: Level,
 	pub line: u32,
-	pub start_time: Instant,
-	pub overall_time: Duration,
+	/// Sum of the wall-clock time between each `enter`/`exit` pair, i.e. time this
+	/// span was actually on the executing thread.
+	pub busy: Duration,
+	/// Wall-clock time from when the span was created to when it closed, minus `busy`,
+	/// i.e. time the span was alive but not executing (e.g. an async task yielding).
+	pub idle: Duration,
 	pub values: Visitor,
 	pub events: Vec<TraceEvent>,
+	/// Ids of spans this span follows from, i.e. causally-related but not parent/child
+	/// (e.g. a task spawned from another), as declared via `tracing::Span::follows_from`.
+	pub follows: Vec<u64>,
 }
 
-/// Responsible for assigning ids to new spans, which are not re-used.
-pub struct ProfilingSubscriber {
-	next_id: AtomicU64,
-	targets: Vec<(String, Level)>,
+/// A `Layer` that collects span/event timing and field data and dispatches it to a
+/// `TraceHandler` on close.
+///
+/// Span storage, id allocation, and current-span tracking are all delegated to the
+/// `Registry` this layer is composed onto (via each span's `extensions`), so it can
+/// be stacked alongside a formatting layer, an `EnvFilter`, or telemetry under one
+/// global dispatcher instead of needing to be the sole `Subscriber`.
+pub struct ProfilingLayer {
+	directives: Vec<Directive>,
 	trace_handler: Box<dyn TraceHandler>,
-	span_data: Mutex<FxHashMap<u64, SpanDatum>>,
-	current_span: CurrentSpan,
+}
+
+/// Per-span timing, held in the span's `Registry` extensions between `new_span` and
+/// `on_close`. Absence of this in a span's extensions means the span was filtered out
+/// (by a directive) when it was created, so it should be ignored on close too.
+struct Timing {
+	created_at: Instant,
+	entered_at: Instant,
+	busy: Duration,
+}
+
+/// Ids of spans a span follows from, held in the span's `Registry` extensions
+/// between `new_span` and `on_close` (see `SpanDatum::follows`).
+struct Follows(Vec<u64>);
+
+/// A single `field[=value]` predicate within a directive's span matcher.
+///
+/// With no `value` the predicate matches any span/event that recorded a field
+/// with this name; with a `value` it additionally requires the recorded value
+/// to stringify to an equal value.
+#[derive(Debug, Clone, PartialEq)]
+struct FieldMatch {
+	name: String,
+	value: Option<String>,
+}
+
+/// A single parsed directive of the form `target[span_name{field=value,field}]=level`.
+///
+/// Every component is optional (a missing `level` parses as `Level::TRACE`): a
+/// directive with no `target`, `span_name` or `fields` acts as the global default
+/// level, and `span_name`/`fields` narrow matching to
+/// spans (and the events nested under them) that carry those fields.
+#[derive(Debug, Clone, PartialEq)]
+struct Directive {
+	target: Option<String>,
+	span_name: Option<String>,
+	fields: Vec<FieldMatch>,
+	level: Level,
+}
+
+/// How specific a directive is: how many of its components are fixed
+/// (non-wildcard), then how long its target prefix is.
+///
+/// Directives are evaluated most-specific-first, so a directive that pins
+/// down target, span name and fields always wins over one that only pins
+/// down a target, which in turn wins over a bare global-default level. Within
+/// the same component count, a longer (more specific) target prefix wins, so
+/// e.g. `pallet_balances::transfer=trace` is checked before `pallet_balances=warn`
+/// rather than the two ties being broken by list order, mirroring the
+/// "module default + override" pattern tracing-subscriber's `EnvFilter` supports.
+fn directive_specificity(directive: &Directive) -> (u8, usize) {
+	let components = directive.target.is_some() as u8
+		+ directive.span_name.is_some() as u8
+		+ !directive.fields.is_empty() as u8;
+	let target_len = directive.target.as_deref().map(str::len).unwrap_or(0);
+	(components, target_len)
+}
+
+/// Splits a directive list on top-level commas, ignoring commas nested inside
+/// the `[...]`/`{...}` span-name-and-fields matcher.
+fn split_directives(spec: &str) -> Vec<&str> {
+	let mut parts = Vec::new();
+	let mut depth = 0i32;
+	let mut start = 0usize;
+	for (i, c) in spec.char_indices() {
+		match c {
+			'[' | '{' => depth += 1,
+			']' | '}' => depth -= 1,
+			',' if depth == 0 => {
+				parts.push(&spec[start..i]);
+				start = i + 1;
+			}
+			_ => {}
+		}
+	}
+	parts.push(&spec[start..]);
+	parts
+}
+
+/// Parses the `span_name{field=value,field}` (or `span_name`, or `{field}`)
+/// contents of a directive's brackets.
+fn parse_span_and_fields(inner: &str) -> (Option<String>, Vec<FieldMatch>) {
+	match inner.find('{') {
+		Some(brace_start) => {
+			let span_name = if brace_start == 0 {
+				None
+			} else {
+				Some(inner[..brace_start].to_string())
+			};
+			let fields = inner[brace_start + 1..]
+				.trim_end_matches('}')
+				.split(',')
+				.map(|f| f.trim())
+				.filter(|f| !f.is_empty())
+				.map(|f| match f.find('=') {
+					Some(i) => FieldMatch { name: f[..i].to_string(), value: Some(f[i + 1..].to_string()) },
+					None => FieldMatch { name: f.to_string(), value: None },
+				})
+				.collect();
+			(span_name, fields)
+		}
+		None => {
+			let name = inner.trim();
+			if name.is_empty() { (None, Vec::new()) } else { (Some(name.to_string()), Vec::new()) }
+		}
+	}
+}
+
+/// Parses a single `target[span_name{field=value,field}]=level` directive.
+///
+/// Defaults to `Level::TRACE` if no level is given or it fails to parse, in
+/// keeping with the previous target-only behaviour.
+fn parse_directive(raw: &str) -> Option<Directive> {
+	let raw = raw.trim();
+	if raw.is_empty() {
+		return None;
+	}
+
+	let (head, level_str) = match raw.find('[') {
+		Some(bracket_start) => {
+			let close = bracket_start + raw[bracket_start..].find(']')?;
+			(&raw[..=close], raw[close + 1..].strip_prefix('='))
+		}
+		None => match raw.rfind('=') {
+			Some(i) => (&raw[..i], Some(&raw[i + 1..])),
+			None => (raw, None),
+		},
+	};
+
+	let (target, span_name, fields) = match head.find('[') {
+		Some(bracket_start) => {
+			let target = if bracket_start == 0 { None } else { Some(head[..bracket_start].to_string()) };
+			let (span_name, fields) = parse_span_and_fields(&head[bracket_start + 1..head.len() - 1]);
+			(target, span_name, fields)
+		}
+		// A bare word with no level and no brackets is either a standalone
+		// level (the global default) or a target prefix.
+		None if level_str.is_none() && head.parse::<Level>().is_ok() => (None, None, Vec::new()),
+		None => (Some(head.to_string()), None, Vec::new()),
+	};
+
+	let level = level_str
+		.and_then(|l| l.parse::<Level>().ok())
+		.or_else(|| if target.is_none() && span_name.is_none() { head.parse::<Level>().ok() } else { None })
+		.unwrap_or(Level::TRACE);
+
+	Some(Directive { target, span_name, fields, level })
+}
+
+/// Parses a comma-separated list of directives, most specific first.
+fn parse_directives(spec: &str) -> Vec<Directive> {
+	let mut directives: Vec<Directive> = split_directives(spec).into_iter().filter_map(parse_directive).collect();
+	directives.sort_by(|a, b| directive_specificity(b).cmp(&directive_specificity(a)));
+	directives
+}
+
+/// A single recorded field value, preserving its original type.
+///
+/// Keeping values typed (rather than collapsing everything to a `String`, as
+/// `Visitor` used to) lets handlers recover numbers and booleans for metrics
+/// without re-parsing, and lets `Serialize` emit native JSON types.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+	I64(i64),
+	U64(u64),
+	Bool(bool),
+	F64(f64),
+	Str(String),
+	Debug(String),
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Value::I64(v) => write!(f, "{}", v),
+			Value::U64(v) => write!(f, "{}", v),
+			Value::Bool(v) => write!(f, "{}", v),
+			Value::F64(v) => write!(f, "{}", v),
+			Value::Str(v) => write!(f, "{}", v),
+			Value::Debug(v) => write!(f, "{}", v),
+		}
+	}
+}
+
+impl Serialize for Value {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where S: Serializer,
+	{
+		match self {
+			Value::I64(v) => serializer.serialize_i64(*v),
+			Value::U64(v) => serializer.serialize_u64(*v),
+			Value::Bool(v) => serializer.serialize_bool(*v),
+			Value::F64(v) => serializer.serialize_f64(*v),
+			Value::Str(v) => serializer.serialize_str(v),
+			Value::Debug(v) => serializer.serialize_str(v),
+		}
+	}
 }
 
 /// Holds associated values for a tracing span
 #[derive(Clone, Debug)]
-pub struct Visitor(FxHashMap<String, String>);
+pub struct Visitor(FxHashMap<String, Value>);
 
 impl Visitor {
 	/// Consume the Visitor, returning the inner FxHashMap
-	pub fn into_inner(self) -> FxHashMap<String, String> {
+	pub fn into_inner(self) -> FxHashMap<String, Value> {
 		self.0
 	}
+
+	/// Whether this visitor's recorded fields satisfy a directive's field predicate:
+	/// presence-only for `field`, stringified equality for `field=value`.
+	fn matches(&self, field_match: &FieldMatch) -> bool {
+		match &field_match.value {
+			Some(expected) => self.0.get(&field_match.name).map(|v| &v.to_string() == expected).unwrap_or(false),
+			None => self.0.contains_key(&field_match.name),
+		}
+	}
 }
 
 impl Visit for Visitor {
 	fn record_i64(&mut self, field: &Field, value: i64) {
-		self.0.insert(field.name().to_string(), value.to_string());
+		self.0.insert(field.name().to_string(), Value::I64(value));
 	}
 
 	fn record_u64(&mut self, field: &Field, value: u64) {
-		self.0.insert(field.name().to_string(), value.to_string());
+		self.0.insert(field.name().to_string(), Value::U64(value));
 	}
 
 	fn record_bool(&mut self, field: &Field, value: bool) {
-		self.0.insert(field.name().to_string(), value.to_string());
+		self.0.insert(field.name().to_string(), Value::Bool(value));
+	}
+
+	fn record_f64(&mut self, field: &Field, value: f64) {
+		self.0.insert(field.name().to_string(), Value::F64(value));
 	}
 
 	fn record_str(&mut self, field: &Field, value: &str) {
-		self.0.insert(field.name().to_string(), value.to_owned());
+		self.0.insert(field.name().to_string(), Value::Str(value.to_owned()));
 	}
 
 	fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
-		self.0.insert(field.name().to_string(), format!("{:?}", value));
+		self.0.insert(field.name().to_string(), Value::Debug(format!("{:?}", value)));
 	}
 }
 
@@ -169,7 +470,7 @@ impl SerdeValue for Visitor {
 	}
 }
 
-impl Value for Visitor {
+impl SlogValue for Visitor {
 	fn serialize(
 		&self,
 		_record: &slog::Record,
@@ -180,69 +481,171 @@ impl Value for Visitor {
 	}
 }
 
-impl ProfilingSubscriber {
-	/// Takes a `TracingReceiver` and a comma separated list of targets,
-	/// either with a level: "pallet=trace,frame=debug"
-	/// or without: "pallet,frame" in which case the level defaults to `trace`.
+/// Walks a span's ancestor chain (innermost first), collecting each span's name and
+/// recorded fields.
+///
+/// Used to match an event's enclosing `span_name`/`fields` directives against the
+/// spans it's actually nested in, since an event's own `Metadata::name()` is an
+/// auto-generated per-callsite string (e.g. `"event src/lib.rs:42"`), never a real
+/// span name.
+fn collect_scope<S>(span: Option<SpanRef<'_, S>>) -> Vec<(String, Visitor)>
+	where S: for<'a> LookupSpan<'a>,
+{
+	let mut scope = Vec::new();
+	let mut current = span;
+	while let Some(span) = current {
+		let fields = span.extensions().get::<Visitor>().cloned()
+			.unwrap_or_else(|| Visitor(FxHashMap::default()));
+		scope.push((span.name().to_owned(), fields));
+		current = span.parent();
+	}
+	scope
+}
+
+/// Finds the most specific directive matching an event's `target` (and, once known,
+/// its own recorded `fields`) and returns whether `level` is enabled by it.
+///
+/// Unlike `ProfilingLayer::check_target`, a directive's `span_name`/`fields`
+/// requirement is checked against `scope` (the event's enclosing spans, as collected
+/// by `collect_scope`) rather than the event itself, since that's what those
+/// directive components are meant to scope to (e.g. `runtime[transfer{who=Alice}]=trace`
+/// should raise the level for events emitted inside a `transfer{who=Alice}` span).
+///
+/// A bare `fields` requirement (no `span_name`) may also be satisfied by the event's
+/// own fields; when those aren't known yet (`fields` is `None`, i.e. called from
+/// `enabled` before the event is recorded), it's matched optimistically so the event
+/// is still recorded, and the precise decision is re-checked once fields are
+/// available, in `on_event`.
+fn check_event_target(
+	directives: &[Directive],
+	target: &str,
+	level: &Level,
+	fields: Option<&Visitor>,
+	scope: &[(String, Visitor)],
+) -> bool {
+	for directive in directives {
+		if let Some(t) = &directive.target {
+			if !target.starts_with(t.as_str()) {
+				continue;
+			}
+		}
+
+		let matched = if let Some(name) = &directive.span_name {
+			scope.iter().any(|(span_name, span_fields)| {
+				span_name == name && directive.fields.iter().all(|f| span_fields.matches(f))
+			})
+		} else if !directive.fields.is_empty() {
+			let in_scope = scope.iter()
+				.any(|(_, span_fields)| directive.fields.iter().all(|f| span_fields.matches(f)));
+			if in_scope {
+				true
+			} else {
+				match fields {
+					Some(values) => directive.fields.iter().all(|f| values.matches(f)),
+					None => true,
+				}
+			}
+		} else {
+			true
+		};
+
+		if matched {
+			return level <= &directive.level;
+		}
+	}
+	false
+}
+
+impl ProfilingLayer {
+	/// Takes a `TracingReceiver` and a comma separated list of directives,
+	/// modeled on tracing-subscriber's env filter: `target[span_name{field=value,field}]=level`,
+	/// where `target`, the bracketed span matcher and the level are all optional, e.g.
+	/// "pallet_balances[transfer{who}]=trace,runtime=debug,info".
 	/// wasm_tracing indicates whether to enable wasm traces
-	pub fn new(receiver: TracingReceiver, targets: &str) -> ProfilingSubscriber {
+	pub fn new(receiver: TracingReceiver, targets: &str) -> ProfilingLayer {
 		match receiver {
 			TracingReceiver::Log => Self::new_with_handler(Box::new(LogTraceHandler), targets),
 			TracingReceiver::Telemetry => Self::new_with_handler(
 				Box::new(TelemetryTraceHandler),
 				targets,
 			),
+			TracingReceiver::Json => Self::new_with_handler(Box::new(JsonTraceHandler), targets),
+			TracingReceiver::File(config) => match FileTraceHandler::new(config) {
+				Ok(handler) => Self::new_with_handler(Box::new(handler), targets),
+				Err(err) => {
+					log::warn!(
+						target: "tracing",
+						"Failed to open trace log file, falling back to logger: {}", err,
+					);
+					Self::new_with_handler(Box::new(LogTraceHandler), targets)
+				}
+			},
 		}
 	}
 
-	/// Allows use of a custom TraceHandler to create a new instance of ProfilingSubscriber.
-	/// Takes a comma separated list of targets,
-	/// either with a level, eg: "pallet=trace"
-	/// or without: "pallet" in which case the level defaults to `trace`.
+	/// Allows use of a custom TraceHandler to create a new instance of ProfilingLayer.
+	/// Takes a comma separated list of directives,
+	/// modeled on tracing-subscriber's env filter: `target[span_name{field=value,field}]=level`,
+	/// where `target`, the bracketed span matcher and the level are all optional.
 	/// wasm_tracing indicates whether to enable wasm traces
 	pub fn new_with_handler(trace_handler: Box<dyn TraceHandler>, targets: &str)
-		-> ProfilingSubscriber
+		-> ProfilingLayer
 	{
-		let targets: Vec<_> = targets.split(',').map(|s| parse_target(s)).collect();
-		ProfilingSubscriber {
-			next_id: AtomicU64::new(1),
-			targets,
+		ProfilingLayer {
+			directives: parse_directives(targets),
 			trace_handler,
-			span_data: Mutex::new(FxHashMap::default()),
-			current_span: Default::default()
 		}
 	}
 
-	fn check_target(&self, target: &str, level: &Level) -> bool {
-		for t in &self.targets {
-			if target.starts_with(t.0.as_str()) && level <= &t.1 {
-				return true;
+	/// Finds the most specific directive matching `target`/`span_name` (and, once
+	/// known, the recorded `fields`) and returns whether `level` is enabled by it.
+	///
+	/// When a directive requires fields that aren't known yet (`fields` is `None`,
+	/// i.e. called from `enabled` before the span's attributes are recorded), it is
+	/// matched optimistically so the span is still created; the precise, field-aware
+	/// decision is re-checked once fields are available, in `new_span`/`on_close`.
+	fn check_target(&self, target: &str, span_name: &str, level: &Level, fields: Option<&Visitor>) -> bool {
+		for directive in &self.directives {
+			if let Some(t) = &directive.target {
+				if !target.starts_with(t.as_str()) {
+					continue;
+				}
+			}
+			if let Some(name) = &directive.span_name {
+				if name != span_name {
+					continue;
+				}
+			}
+			if !directive.fields.is_empty() {
+				match fields {
+					Some(values) if directive.fields.iter().all(|f| values.matches(f)) => {},
+					Some(_) => continue,
+					None => return level <= &directive.level,
+				}
 			}
+			return level <= &directive.level;
 		}
 		false
 	}
 }
 
-// Default to TRACE if no level given or unable to parse Level
-// We do not support a global `Level` currently
-fn parse_target(s: &str) -> (String, Level) {
-	match s.find('=') {
-		Some(i) => {
-			let target = s[0..i].to_string();
-			if s.len() > i {
-				let level = s[i + 1..s.len()].parse::<Level>().unwrap_or(Level::TRACE);
-				(target, level)
-			} else {
-				(target, Level::TRACE)
-			}
-		}
-		None => (s.to_string(), Level::TRACE)
+impl<S> Layer<S> for ProfilingLayer
+	where S: Subscriber + for<'a> LookupSpan<'a>,
+{
+	// An event's `enabled` decision depends on its enclosing span scope, which isn't
+	// known at callsite registration, so it must not be cached per callsite.
+	fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> tracing_core::subscriber::Interest {
+		tracing_core::subscriber::Interest::sometimes()
 	}
-}
 
-impl Subscriber for ProfilingSubscriber {
-	fn enabled(&self, metadata: &Metadata<'_>) -> bool {
-		if metadata.target() == PROXY_TARGET || self.check_target(metadata.target(), metadata.level()) {
+	fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+		let target_enabled = if metadata.is_event() {
+			let scope = collect_scope(ctx.lookup_current());
+			check_event_target(&self.directives, metadata.target(), metadata.level(), None, &scope)
+		} else {
+			self.check_target(metadata.target(), metadata.name(), metadata.level(), None)
+		};
+		if metadata.target() == PROXY_TARGET || target_enabled {
 			log::debug!(target: "tracing", "Enabled target: {}, level: {}", metadata.target(), metadata.level());
 			true
 		} else {
@@ -251,70 +654,90 @@ impl Subscriber for ProfilingSubscriber {
 		}
 	}
 
-	fn new_span(&self, attrs: &Attributes<'_>) -> Id {
-		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+	fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
 		let mut values = Visitor(FxHashMap::default());
 		attrs.record(&mut values);
 		// If this is a wasm trace, check if target/level is enabled
-		if let Some(wasm_target) = values.0.get(WASM_TARGET_KEY) {
-			if !self.check_target(wasm_target, attrs.metadata().level()) {
-				return Id::from_u64(id);
-			}
-		}
-		let span_datum = SpanDatum {
-			id,
-			parent_id: self.current_span.id().map(|p| p.into_u64()),
-			name: attrs.metadata().name().to_owned(),
-			target: attrs.metadata().target().to_owned(),
-			level: attrs.metadata().level().clone(),
-			line: attrs.metadata().line().unwrap_or(0),
-			start_time: Instant::now(),
-			overall_time: ZERO_DURATION,
-			values,
-			events: Vec::new(),
+		let enabled = if let Some(wasm_target) = values.0.get(WASM_TARGET_KEY).map(|v| v.to_string()) {
+			self.check_target(&wasm_target, attrs.metadata().name(), attrs.metadata().level(), Some(&values))
+		} else {
+			self.check_target(
+				attrs.metadata().target(),
+				attrs.metadata().name(),
+				attrs.metadata().level(),
+				Some(&values),
+			)
 		};
-		self.span_data.lock().insert(id, span_datum);
-		Id::from_u64(id)
+		if !enabled {
+			return;
+		}
+		let span = ctx.span(id).expect("Span should exist in the registry; qed");
+		let mut extensions = span.extensions_mut();
+		extensions.insert(values);
+		let created_at = Instant::now();
+		extensions.insert(Timing { created_at, entered_at: created_at, busy: ZERO_DURATION });
+		extensions.insert(Vec::<TraceEvent>::new());
+		extensions.insert(Follows(Vec::new()));
 	}
 
-	fn record(&self, span: &Id, values: &Record<'_>) {
-		let mut span_data = self.span_data.lock();
-		if let Some(s) = span_data.get_mut(&span.into_u64()) {
-			values.record(&mut s.values);
+	fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			let mut extensions = span.extensions_mut();
+			if let Some(visitor) = extensions.get_mut::<Visitor>() {
+				values.record(visitor);
+			}
 		}
 	}
 
-	fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+	fn on_follows_from(&self, span: &Id, follows: &Id, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(span) {
+			let mut extensions = span.extensions_mut();
+			if let Some(Follows(follows_ids)) = extensions.get_mut::<Follows>() {
+				follows_ids.push(follows.into_u64());
+			}
+		}
+	}
 
-	fn event(&self, event: &Event<'_>) {
+	fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
 		let mut visitor = Visitor(FxHashMap::default());
 		event.record(&mut visitor);
-		let trace_event = TraceEvent {
+		let parent_span = ctx.event_span(event);
+		let mut trace_event = TraceEvent {
 			name: event.metadata().name(),
 			target: event.metadata().target().to_owned(),
 			level: event.metadata().level().clone(),
 			visitor,
-			parent_id: self.current_span.id().map(|id| id.into_u64()),
+			parent_id: parent_span.as_ref().map(|s| s.id().into_u64()),
 		};
+		// `enabled` already passed this target/level, but optimistically for a bare
+		// `fields` requirement (see `check_event_target`'s doc) since an event's own
+		// fields aren't known until now. Re-check with the recorded fields, against
+		// the same enclosing span scope, so directives actually filter out events
+		// whose span/fields don't match.
+		let scope = collect_scope(ctx.event_span(event));
+		if !check_event_target(&self.directives, &trace_event.target, &trace_event.level, Some(&trace_event.visitor), &scope) {
+			return;
+		}
 		// Q: Should all events be emitted immediately, rather than grouping with parent span?
-		match trace_event.parent_id {
-			Some(parent_id) => {
-				if let Some(span) = self.span_data.lock().get_mut(&parent_id) {
-					if span.events.len() > LEN_LIMIT {
+		match parent_span {
+			Some(span) => {
+				let mut extensions = span.extensions_mut();
+				if let Some(events) = extensions.get_mut::<Vec<TraceEvent>>() {
+					if events.len() > LEN_LIMIT {
 						log::warn!(
 							target: "tracing",
 							"Accumulated too many events for span id: {}, sending event separately",
-							parent_id
+							span.id().into_u64(),
 						);
+						drop(extensions);
 						self.trace_handler.process_event(trace_event);
 					} else {
-						span.events.push(trace_event);
+						events.push(trace_event);
 					}
 				} else {
-					log::warn!(
-						target: "tracing",
-						"Parent span missing"
-					);
+					// The parent span was filtered out at `new_span` time.
+					drop(extensions);
+					trace_event.parent_id = None;
 					self.trace_handler.process_event(trace_event);
 				}
 			}
@@ -322,44 +745,68 @@ impl Subscriber for ProfilingSubscriber {
 		}
 	}
 
-	fn enter(&self, span: &Id) {
-		self.current_span.enter(span.clone());
-		let mut span_data = self.span_data.lock();
-		let start_time = Instant::now();
-		if let Some(mut s) = span_data.get_mut(&span.into_u64()) {
-			s.start_time = start_time;
+	fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			let mut extensions = span.extensions_mut();
+			if let Some(timing) = extensions.get_mut::<Timing>() {
+				timing.entered_at = Instant::now();
+			}
 		}
 	}
 
-	fn exit(&self, span: &Id) {
-		self.current_span.exit();
+	fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
 		let end_time = Instant::now();
-		let mut span_data = self.span_data.lock();
-		if let Some(mut s) = span_data.get_mut(&span.into_u64()) {
-			s.overall_time = end_time - s.start_time + s.overall_time;
+		if let Some(span) = ctx.span(id) {
+			let mut extensions = span.extensions_mut();
+			if let Some(timing) = extensions.get_mut::<Timing>() {
+				timing.busy += end_time - timing.entered_at;
+			}
 		}
 	}
 
-	fn try_close(&self, span: Id) -> bool {
-		let span_datum = {
-			let mut span_data = self.span_data.lock();
-			span_data.remove(&span.into_u64())
+	fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+		let span = match ctx.span(&id) {
+			Some(span) => span,
+			None => return,
 		};
-		if let Some(mut span_datum) = span_datum {
-			if span_datum.name == WASM_TRACE_IDENTIFIER {
-				span_datum.values.0.insert("wasm".to_owned(), "true".to_owned());
-				if let Some(n) = span_datum.values.0.remove(WASM_NAME_KEY) {
-					span_datum.name = n;
-				}
-				if let Some(t) = span_datum.values.0.remove(WASM_TARGET_KEY) {
-					span_datum.target = t;
-				}
+		let mut extensions = span.extensions_mut();
+		let timing = match extensions.remove::<Timing>() {
+			Some(timing) => timing,
+			// The span was filtered out at `new_span` time.
+			None => return,
+		};
+		let values = extensions.remove::<Visitor>().unwrap_or_else(|| Visitor(FxHashMap::default()));
+		let events = extensions.remove::<Vec<TraceEvent>>().unwrap_or_default();
+		let follows = extensions.remove::<Follows>().map(|f| f.0).unwrap_or_default();
+		drop(extensions);
+
+		let idle = Instant::now().saturating_duration_since(timing.created_at).saturating_sub(timing.busy);
+		let metadata = span.metadata();
+		let mut span_datum = SpanDatum {
+			id: id.into_u64(),
+			parent_id: span.parent().map(|p| p.id().into_u64()),
+			name: metadata.name().to_owned(),
+			target: metadata.target().to_owned(),
+			level: metadata.level().clone(),
+			line: metadata.line().unwrap_or(0),
+			busy: timing.busy,
+			idle,
+			values,
+			events,
+			follows,
+		};
+		if span_datum.name == WASM_TRACE_IDENTIFIER {
+			span_datum.values.0.insert("wasm".to_owned(), Value::Bool(true));
+			if let Some(n) = span_datum.values.0.remove(WASM_NAME_KEY) {
+				span_datum.name = n.to_string();
 			}
-			if self.check_target(&span_datum.target, &span_datum.level) {
-				self.trace_handler.process_span(span_datum);
+			if let Some(t) = span_datum.values.0.remove(WASM_TARGET_KEY) {
+				span_datum.target = t.to_string();
 			}
-		};
-		true
+		}
+		if self.check_target(&span_datum.target, &span_datum.name, &span_datum.level, Some(&span_datum.values)) {
+			self.trace_handler.process_span(span_datum);
+		}
 	}
 }
 
@@ -381,23 +828,27 @@ impl TraceHandler for LogTraceHandler {
 		if span_datum.values.0.is_empty() {
 			log::log!(
 				log_level(span_datum.level),
-				"{}: {}, time: {}, id: {}, parent_id: {:?}, events: {:?}",
+				"{}: {}, busy: {}, idle: {}, id: {}, parent_id: {:?}, follows_from: {:?}, events: {:?}",
 				span_datum.target,
 				span_datum.name,
-				span_datum.overall_time.as_nanos(),
+				span_datum.busy.as_nanos(),
+				span_datum.idle.as_nanos(),
 				span_datum.id,
 				span_datum.parent_id,
+				span_datum.follows,
 				span_datum.events,
 			);
 		} else {
 			log::log!(
 				log_level(span_datum.level),
-				"{}: {}, time: {}, id: {}, parent_id: {:?}, values: {}, events: {:?}",
+				"{}: {}, busy: {}, idle: {}, id: {}, parent_id: {:?}, follows_from: {:?}, values: {}, events: {:?}",
 				span_datum.target,
 				span_datum.name,
-				span_datum.overall_time.as_nanos(),
+				span_datum.busy.as_nanos(),
+				span_datum.idle.as_nanos(),
 				span_datum.id,
 				span_datum.parent_id,
+				span_datum.follows,
 				span_datum.values,
 				span_datum.events,
 			);
@@ -426,9 +877,11 @@ impl TraceHandler for TelemetryTraceHandler {
 		telemetry!(SUBSTRATE_INFO; "tracing.span";
 			"name" => span_datum.name,
 			"target" => span_datum.target,
-			"time" => span_datum.overall_time.as_nanos(),
+			"busy" => span_datum.busy.as_nanos(),
+			"idle" => span_datum.idle.as_nanos(),
 			"id" => span_datum.id,
 			"parent_id" => span_datum.parent_id,
+			"follows_from" => format!("{:?}", span_datum.follows),
 			"values" => span_datum.values
 		);
 	}
@@ -443,10 +896,162 @@ impl TraceHandler for TelemetryTraceHandler {
 	}
 }
 
+/// TraceHandler that serializes each `SpanDatum`/`TraceEvent` as a single
+/// newline-delimited JSON object to stdout, following the field layout
+/// tracing-subscriber's JSON formatter uses. This lets operators pipe node
+/// profiling output straight into log-aggregation pipelines.
+pub struct JsonTraceHandler;
+
+fn event_to_json(event: &TraceEvent) -> serde_json::Value {
+	json!({
+		"name": event.name,
+		"target": event.target,
+		"level": event.level.to_string(),
+		"parent_id": event.parent_id,
+		"fields": event.visitor,
+	})
+}
+
+impl TraceHandler for JsonTraceHandler {
+	fn process_span(&self, span_datum: SpanDatum) {
+		let payload = json!({
+			"name": span_datum.name,
+			"target": span_datum.target,
+			"level": span_datum.level.to_string(),
+			"id": span_datum.id,
+			"parent_id": span_datum.parent_id,
+			"follows_from": span_datum.follows,
+			"busy_ns": span_datum.busy.as_nanos() as u64,
+			"idle_ns": span_datum.idle.as_nanos() as u64,
+			"fields": span_datum.values,
+			"events": span_datum.events.iter().map(event_to_json).collect::<Vec<_>>(),
+		});
+		println!("{}", payload);
+	}
+
+	fn process_event(&self, event: TraceEvent) {
+		println!("{}", event_to_json(&event));
+	}
+}
+
+/// TraceHandler that writes span/event output to an on-disk file, rotating it on
+/// an hourly/daily/never schedule, mirroring tracing-appender's rolling writer.
+/// Each rotated file is named `{prefix}.{date}.{suffix}`, with the date component
+/// only present when `rotation` isn't `Rotation::Never` and the suffix only when
+/// one is configured.
+pub struct FileTraceHandler {
+	inner: Mutex<FileTraceHandlerState>,
+}
+
+struct FileTraceHandlerState {
+	config: FileConfig,
+	file: File,
+	next_rotation_at: Option<DateTime<Utc>>,
+}
+
+impl FileTraceHandler {
+	/// Opens the initial log file for `config`.
+	pub fn new(config: FileConfig) -> std::io::Result<FileTraceHandler> {
+		let now = Utc::now();
+		let file = Self::open_file(&config, now)?;
+		let next_rotation_at = config.rotation.next_boundary_after(now);
+		Ok(FileTraceHandler { inner: Mutex::new(FileTraceHandlerState { config, file, next_rotation_at }) })
+	}
+
+	fn file_name(config: &FileConfig, now: DateTime<Utc>) -> String {
+		let mut name = config.prefix.clone();
+		if let Some(format) = config.rotation.date_format() {
+			name.push('.');
+			name.push_str(&now.format(format).to_string());
+		}
+		if let Some(suffix) = &config.suffix {
+			name.push('.');
+			name.push_str(suffix);
+		}
+		name
+	}
+
+	fn open_file(config: &FileConfig, now: DateTime<Utc>) -> std::io::Result<File> {
+		OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(config.directory.join(Self::file_name(config, now)))
+	}
+
+	/// Rotates to a new file if the current time has crossed the next rotation boundary.
+	fn rotate_if_needed(state: &mut FileTraceHandlerState) {
+		let next_rotation_at = match state.next_rotation_at {
+			Some(t) => t,
+			None => return,
+		};
+		let now = Utc::now();
+		if now < next_rotation_at {
+			return;
+		}
+		match Self::open_file(&state.config, now) {
+			Ok(file) => {
+				state.file = file;
+				state.next_rotation_at = state.config.rotation.next_boundary_after(now);
+			}
+			Err(err) => log::warn!(target: "tracing", "Failed to rotate trace log file: {}", err),
+		}
+	}
+
+	fn write_line(&self, line: &str) {
+		let mut state = self.inner.lock();
+		Self::rotate_if_needed(&mut state);
+		if let Err(err) = writeln!(state.file, "{}", line) {
+			log::warn!(target: "tracing", "Failed to write to trace log file: {}", err);
+		}
+	}
+}
+
+impl TraceHandler for FileTraceHandler {
+	fn process_span(&self, span_datum: SpanDatum) {
+		if span_datum.values.0.is_empty() {
+			self.write_line(&format!(
+				"{}: {}, busy: {}, idle: {}, id: {}, parent_id: {:?}, follows_from: {:?}, events: {:?}",
+				span_datum.target,
+				span_datum.name,
+				span_datum.busy.as_nanos(),
+				span_datum.idle.as_nanos(),
+				span_datum.id,
+				span_datum.parent_id,
+				span_datum.follows,
+				span_datum.events,
+			));
+		} else {
+			self.write_line(&format!(
+				"{}: {}, busy: {}, idle: {}, id: {}, parent_id: {:?}, follows_from: {:?}, values: {}, events: {:?}",
+				span_datum.target,
+				span_datum.name,
+				span_datum.busy.as_nanos(),
+				span_datum.idle.as_nanos(),
+				span_datum.id,
+				span_datum.parent_id,
+				span_datum.follows,
+				span_datum.values,
+				span_datum.events,
+			));
+		}
+	}
+
+	fn process_event(&self, event: TraceEvent) {
+		self.write_line(&format!(
+			"{}: {}, parent_id: {:?}, values: {}",
+			event.name,
+			event.target,
+			event.parent_id,
+			event.visitor,
+		));
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 	use std::sync::Arc;
+	use tracing_subscriber::layer::SubscriberExt;
 
 	struct TestTraceHandler{
 		spans: Arc<Mutex<Vec<SpanDatum>>>,
@@ -463,18 +1068,206 @@ mod tests {
 		}
 	}
 
-	fn setup_subscriber() -> (ProfilingSubscriber, Arc<Mutex<Vec<SpanDatum>>>, Arc<Mutex<Vec<TraceEvent>>>) {
+	fn setup_subscriber() -> (impl Subscriber, Arc<Mutex<Vec<SpanDatum>>>, Arc<Mutex<Vec<TraceEvent>>>) {
+		setup_subscriber_with_directives("test_target")
+	}
+
+	fn setup_subscriber_with_directives(directives: &str)
+		-> (impl Subscriber, Arc<Mutex<Vec<SpanDatum>>>, Arc<Mutex<Vec<TraceEvent>>>)
+	{
 		let spans = Arc::new(Mutex::new(Vec::new()));
 		let events = Arc::new(Mutex::new(Vec::new()));
 		let handler = TestTraceHandler {
 			spans: spans.clone(),
 			events: events.clone(),
 		};
-		let test_subscriber = ProfilingSubscriber::new_with_handler(
+		let layer = ProfilingLayer::new_with_handler(
 			Box::new(handler),
-			"test_target"
+			directives,
+		);
+		let subscriber = tracing_subscriber::Registry::default().with(layer);
+		(subscriber, spans, events)
+	}
+
+	#[test]
+	fn directive_parses_bare_target() {
+		assert_eq!(
+			parse_directive("foo"),
+			Some(Directive { target: Some("foo".to_owned()), span_name: None, fields: Vec::new(), level: Level::TRACE }),
+		);
+	}
+
+	#[test]
+	fn directive_parses_bare_level_as_global_default() {
+		assert_eq!(
+			parse_directive("debug"),
+			Some(Directive { target: None, span_name: None, fields: Vec::new(), level: Level::DEBUG }),
+		);
+	}
+
+	#[test]
+	fn directive_parses_target_and_span() {
+		assert_eq!(
+			parse_directive("foo[myspan]=debug"),
+			Some(Directive {
+				target: Some("foo".to_owned()),
+				span_name: Some("myspan".to_owned()),
+				fields: Vec::new(),
+				level: Level::DEBUG,
+			}),
+		);
+	}
+
+	#[test]
+	fn directive_parses_target_span_and_field_presence() {
+		assert_eq!(
+			parse_directive("foo[myspan{bar}]=info"),
+			Some(Directive {
+				target: Some("foo".to_owned()),
+				span_name: Some("myspan".to_owned()),
+				fields: vec![FieldMatch { name: "bar".to_owned(), value: None }],
+				level: Level::INFO,
+			}),
+		);
+	}
+
+	#[test]
+	fn directive_parses_target_span_and_field_equality() {
+		assert_eq!(
+			parse_directive("foo[myspan{bar=baz}]=warn"),
+			Some(Directive {
+				target: Some("foo".to_owned()),
+				span_name: Some("myspan".to_owned()),
+				fields: vec![FieldMatch { name: "bar".to_owned(), value: Some("baz".to_owned()) }],
+				level: Level::WARN,
+			}),
 		);
-		(test_subscriber, spans, events)
+	}
+
+	#[test]
+	fn parse_directives_breaks_specificity_ties_in_favour_of_the_first_listed() {
+		// Both directives target "foo" with no span/fields, so they tie on specificity;
+		// the sort must be stable and keep "foo=warn" ahead of "foo=trace".
+		let directives = parse_directives("foo=warn,foo=trace");
+		assert_eq!(directives[0].level, Level::WARN);
+		assert_eq!(directives[1].level, Level::TRACE);
+
+		let layer = ProfilingLayer::new_with_handler(
+			Box::new(LogTraceHandler),
+			"foo=warn,foo=trace",
+		);
+		// TRACE is more verbose than the first-listed "foo=warn" allows, even though a
+		// later, looser "foo=trace" directive would have allowed it.
+		assert!(!layer.check_target("foo", "some_span", &Level::TRACE, None));
+	}
+
+	#[test]
+	fn parse_directives_breaks_ties_by_target_length_not_list_order() {
+		// "pallet_balances::transfer" is strictly more specific than "pallet_balances",
+		// so it must be checked first even though it's listed second and both directives
+		// tie on component count (target only).
+		let layer = ProfilingLayer::new_with_handler(
+			Box::new(LogTraceHandler),
+			"pallet_balances=warn,pallet_balances::transfer=trace",
+		);
+		assert!(layer.check_target("pallet_balances::transfer", "some_span", &Level::TRACE, None));
+		assert!(!layer.check_target("pallet_balances::other", "some_span", &Level::TRACE, None));
+	}
+
+	#[test]
+	fn check_target_enforces_field_equality_predicates() {
+		let layer = ProfilingLayer::new_with_handler(
+			Box::new(LogTraceHandler),
+			"runtime[transfer{who=Alice}]=trace",
+		);
+		let mut matching = Visitor(FxHashMap::default());
+		matching.0.insert("who".to_owned(), Value::Str("Alice".to_owned()));
+		assert!(layer.check_target("runtime", "transfer", &Level::TRACE, Some(&matching)));
+
+		let mut other = Visitor(FxHashMap::default());
+		other.0.insert("who".to_owned(), Value::Str("Bob".to_owned()));
+		assert!(!layer.check_target("runtime", "transfer", &Level::TRACE, Some(&other)));
+	}
+
+	#[test]
+	fn check_event_target_matches_span_name_and_fields_from_enclosing_scope() {
+		let directives = parse_directives("runtime[transfer{who=Alice}]=trace");
+		let mut transfer_fields = Visitor(FxHashMap::default());
+		transfer_fields.0.insert("who".to_owned(), Value::Str("Alice".to_owned()));
+		let scope = vec![("transfer".to_owned(), transfer_fields)];
+
+		// An event's own pseudo-name/fields never match `span_name`/`fields`; only the
+		// enclosing span in `scope` should.
+		assert!(check_event_target(&directives, "runtime", &Level::TRACE, None, &scope));
+
+		let mut other_fields = Visitor(FxHashMap::default());
+		other_fields.0.insert("who".to_owned(), Value::Str("Bob".to_owned()));
+		let mismatched_scope = vec![("transfer".to_owned(), other_fields)];
+		assert!(!check_event_target(&directives, "runtime", &Level::TRACE, None, &mismatched_scope));
+
+		assert!(!check_event_target(&directives, "runtime", &Level::TRACE, None, &[]));
+	}
+
+	#[test]
+	fn test_event_inside_a_matching_span_is_recorded() {
+		let (sub, spans, _events) = setup_subscriber_with_directives("runtime[transfer{who=Alice}]=trace");
+		let _sub_guard = tracing::subscriber::set_default(sub);
+		let span = tracing::info_span!(target: "runtime", "transfer", who = "Alice");
+		let _guard = span.enter();
+		tracing::event!(target: "runtime", tracing::Level::TRACE, "moved funds");
+		drop(_guard);
+		drop(span);
+
+		let sd = spans.lock().remove(0);
+		assert_eq!(sd.events.len(), 1);
+		assert_eq!(sd.events[0].target, "runtime");
+	}
+
+	#[test]
+	fn event_to_json_has_the_expected_shape() {
+		let mut visitor = Visitor(FxHashMap::default());
+		visitor.0.insert("message".to_owned(), Value::Str("hello".to_owned()));
+		let event = TraceEvent {
+			name: "test_event",
+			target: "test_target".to_owned(),
+			level: Level::INFO,
+			visitor,
+			parent_id: Some(42),
+		};
+		let json = event_to_json(&event);
+		assert_eq!(json["name"], "test_event");
+		assert_eq!(json["target"], "test_target");
+		assert_eq!(json["level"], "INFO");
+		assert_eq!(json["parent_id"], 42);
+		assert_eq!(json["fields"]["message"], "hello");
+	}
+
+	#[test]
+	fn hourly_rotation_boundary_is_aligned_to_the_hour() {
+		use chrono::TimeZone;
+		let mid_hour = Utc.ymd(2021, 1, 1).and_hms(13, 45, 0);
+		let boundary = Rotation::Hourly.next_boundary_after(mid_hour).unwrap();
+		assert_eq!(boundary, Utc.ymd(2021, 1, 1).and_hms(14, 0, 0));
+
+		// The boundary after the boundary itself should be a full period later, not
+		// drift forward from whatever second rotation happened to run at.
+		let next_boundary = Rotation::Hourly.next_boundary_after(boundary).unwrap();
+		assert_eq!(next_boundary, Utc.ymd(2021, 1, 1).and_hms(15, 0, 0));
+	}
+
+	#[test]
+	fn daily_rotation_boundary_is_aligned_to_midnight() {
+		use chrono::TimeZone;
+		let mid_day = Utc.ymd(2021, 1, 1).and_hms(13, 45, 0);
+		let boundary = Rotation::Daily.next_boundary_after(mid_day).unwrap();
+		assert_eq!(boundary, Utc.ymd(2021, 1, 2).and_hms(0, 0, 0));
+	}
+
+	#[test]
+	fn never_rotation_has_no_boundary() {
+		use chrono::TimeZone;
+		let now = Utc.ymd(2021, 1, 1).and_hms(13, 45, 0);
+		assert_eq!(Rotation::Never.next_boundary_after(now), None);
 	}
 
 	#[test]
@@ -494,8 +1287,28 @@ mod tests {
 		let sd = spans.lock().remove(0);
 		assert_eq!(sd.name, "test_span1");
 		assert_eq!(sd.target, "test_target");
-		let time: u128 = sd.overall_time.as_nanos();
-		assert!(time > 0);
+		let busy: u128 = sd.busy.as_nanos();
+		assert!(busy > 0);
+	}
+
+	#[test]
+	fn idle_time_accrues_while_a_span_is_not_entered() {
+		let (sub, spans, _events) = setup_subscriber();
+		let _sub_guard = tracing::subscriber::set_default(sub);
+		let span = tracing::info_span!(target: "test_target", "test_span1");
+
+		// Enter and exit once, then sit outside the span (simulating an async task
+		// yielding) before entering again, so both `busy` and `idle` accumulate.
+		let _guard = span.enter();
+		drop(_guard);
+		std::thread::sleep(std::time::Duration::from_millis(5));
+		let _guard = span.enter();
+		drop(_guard);
+		drop(span);
+
+		let sd = spans.lock().remove(0);
+		assert!(sd.busy.as_nanos() > 0);
+		assert!(sd.idle.as_nanos() > 0);
 	}
 
 	#[test]
@@ -515,13 +1328,28 @@ mod tests {
 		assert_eq!(sd1.id, sd2.parent_id.unwrap())
 	}
 
+	#[test]
+	fn follows_from_is_recorded_on_the_span_datum() {
+		let (sub, spans, _events) = setup_subscriber();
+		let _sub_guard = tracing::subscriber::set_default(sub);
+		let predecessor = tracing::info_span!(target: "test_target", "predecessor");
+		let predecessor_id = predecessor.id().unwrap().into_u64();
+		let span = tracing::info_span!(target: "test_target", "test_span1");
+		span.follows_from(&predecessor);
+		drop(span);
+		drop(predecessor);
+
+		let sd = spans.lock().iter().find(|sd| sd.name == "test_span1").unwrap().follows.clone();
+		assert_eq!(sd, vec![predecessor_id]);
+	}
+
 	#[test]
 	fn test_event() {
 		let (sub, _spans, events) = setup_subscriber();
 		let _sub_guard = tracing::subscriber::set_default(sub);
 		tracing::event!(target: "test_target", tracing::Level::INFO, "test_event");
 		let mut te1 = events.lock().remove(0);
-		assert_eq!(te1.visitor.0.remove(&"message".to_owned()).unwrap(), "test_event".to_owned());
+		assert_eq!(te1.visitor.0.remove(&"message".to_owned()).unwrap(), Value::Str("test_event".to_owned()));
 	}
 
 	#[test]